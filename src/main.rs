@@ -1,8 +1,10 @@
 #[macro_use] extern crate log;
 
 use clap::Parser;
-use rust_htslib::bcf::{Header, HeaderRecord, Reader, Read, Writer, Format};
-use rust_htslib::bcf::header;
+use rust_htslib::bcf::{Header, HeaderRecord, IndexedReader, Reader, Read, Record, Writer, Format};
+use rust_htslib::bcf::header::{self, HeaderView};
+use rust_htslib::bcf::record::Numeric;
+use rust_htslib::errors::Result as HtsResult;
 use std::collections::{BTreeMap, BTreeSet};
 
 /// Simple program to copy INFO fields to FORMAT tags
@@ -25,6 +27,38 @@ struct Args {
    #[arg(short, long)]
    qual: bool,
 
+   /// Transfer also the FILTER column into a per-sample FT FORMAT tag
+   #[arg(long)]
+   filter: bool,
+
+   /// Accept multi-sample input and broadcast the transferred values to every sample
+   #[arg(long)]
+   broadcast: bool,
+
+   /// Restrict processing to a region "chr", "chr:pos" or "chr:start-end" (1-based, inclusive);
+   /// requires a file path input with an index
+   #[arg(long)]
+   region: Option<String>,
+
+   /// Output format: 'b' compressed BCF, 'u' uncompressed BCF, 'z' compressed VCF, 'v' uncompressed VCF.
+   /// Defaults to guessing from the output file extension (bcftools-style)
+   #[arg(long = "output-type")]
+   output_type: Option<String>,
+
+   /// Reverse the operation: lift the given FORMAT tags back into INFO (single-sample only)
+   #[arg(long)]
+   reverse: bool,
+
+   /// Prefix applied to every transferred field's FORMAT ID, e.g. "INFO_", to avoid
+   /// clashing with an existing FORMAT tag of the same name
+   #[arg(long)]
+   prefix: Option<String>,
+
+   /// Rename a specific transferred field's FORMAT ID as "OLD:NEW" (repeatable),
+   /// taking precedence over '--prefix' for that field
+   #[arg(long)]
+   rename: Vec<String>,
+
    /// Show verbose output (sets log-level to debug or trace)
    #[arg(short, long, action = clap::ArgAction::Count)]
     verbose: u8,
@@ -41,6 +75,100 @@ enum TagValue {
 	String(Vec<Vec<u8>>)
 }
 
+/// Either a plain streaming reader or an indexed reader restricted to a `--region`.
+enum Input {
+	Plain(Reader),
+	Indexed(IndexedReader),
+}
+
+impl Input {
+	fn header(&self) -> &HeaderView {
+		match self {
+			Input::Plain(r) => r.header(),
+			Input::Indexed(r) => r.header(),
+		}
+	}
+
+	fn empty_record(&self) -> Record {
+		match self {
+			Input::Plain(r) => r.empty_record(),
+			Input::Indexed(r) => r.empty_record(),
+		}
+	}
+
+	fn read(&mut self, record: &mut Record) -> Option<HtsResult<()>> {
+		match self {
+			Input::Plain(r) => r.read(record),
+			Input::Indexed(r) => r.read(record),
+		}
+	}
+}
+
+/// Parse a bcftools-style region string ("chr", "chr:pos" or "chr:start-end", 1-based
+/// inclusive) into a contig name plus 0-based start/end coordinates for `IndexedReader::fetch`.
+/// Returns `None` if the coordinates are not valid numbers.
+fn parse_region(region: &str) -> Option<(String, u64, Option<u64>)> {
+	match region.split_once(':') {
+		None => Some((region.to_owned(), 0, None)),
+		Some((contig, range)) => match range.split_once('-') {
+			Some((start, end)) => {
+				let start: u64 = start.parse().ok()?;
+				let end: u64 = end.parse().ok()?;
+				Some((contig.to_owned(), start.saturating_sub(1), Some(end.saturating_sub(1))))
+			},
+			None => {
+				let pos: u64 = range.parse().ok()?;
+				Some((contig.to_owned(), pos.saturating_sub(1), Some(pos.saturating_sub(1))))
+			}
+		}
+	}
+}
+
+/// Resolve the output `Format` and compression flag from an explicit `--output-type`
+/// (mirroring bcftools' b|u|z|v) or, failing that, from the output path's extension.
+fn resolve_output_format(output: &str, output_type: &Option<String>) -> Option<(Format, bool)> {
+	if let Some(t) = output_type {
+		return match t.as_str() {
+			"b" => Some((Format::Bcf, false)),
+			"u" => Some((Format::Bcf, true)),
+			"z" => Some((Format::Vcf, false)),
+			"v" => Some((Format::Vcf, true)),
+			_ => None,
+		};
+	}
+
+	if output.ends_with(".bcf") {
+		Some((Format::Bcf, false))
+	} else if output.ends_with(".vcf.gz") || output.ends_with(".bgz") {
+		Some((Format::Vcf, false))
+	} else {
+		Some((Format::Vcf, true))
+	}
+}
+
+/// Replicate a site-level value vector once per sample, preserving the per-sample
+/// stride, so the FORMAT buffer stays rectangular across all `n_samples` samples.
+fn broadcast<T: Clone>(values: &[T], n_samples: usize) -> Vec<T> {
+	values.iter().cloned().cycle().take(values.len() * n_samples).collect()
+}
+
+/// Render the site-level FILTER column as a value suitable for a per-sample FT tag,
+/// joining multiple filters with ';'. An explicit "PASS" FILTER is stored as the PASS
+/// filter id and rendered as "PASS"; an unset (".") FILTER has no filter ids and is
+/// rendered as ".".
+fn filter_string(rec: &rust_htslib::bcf::Record) -> String {
+	let header = rec.header();
+	let names: Vec<String> = rec.filters()
+		.map(|id| String::from_utf8_lossy(&header.id_to_name(id)).into_owned())
+		.collect();
+
+	if names.is_empty() {
+		".".to_owned()
+	} else {
+		names.join(";")
+	}
+}
+
 fn main() {
   env_logger::init();
 
@@ -55,8 +183,13 @@ fn main() {
     log::set_max_level(log::LevelFilter::Info)
   }
 
-  if args.fields.len() == 0 && !args.qual {
-		error!("No field for conversion identified. Use '-q' or '-f' options");
+  if args.reverse && (args.qual || args.filter || args.broadcast) {
+		error!("'--reverse' cannot be combined with '-q', '--filter' or '--broadcast'");
+		return;
+	}
+
+  if args.fields.len() == 0 && !args.qual && !args.filter {
+		error!("No field for conversion identified. Use '-q', '-f' or '--filter' options");
 		return;
 	}
 
@@ -64,47 +197,157 @@ fn main() {
 		.map(|x| x.to_owned())
 		.collect();
 
-	// Open the BAM File and extract information from the header
-	let mut input = if args.input == "-" {
+	// Open the input VCF/BCF and extract information from the header
+	let mut input = if let Some(region) = &args.region {
+	  if args.input == "-" {
+	    error!("--region requires a file path input, not STDIN");
+	    return;
+	  }
+	  debug!("Opening indexed input at {} and seeking to region {}", args.input, region);
+	  let mut reader = match IndexedReader::from_path(&args.input) {
+	    Ok(r) => r,
+	    Err(_) => {
+	      error!("Can not open indexed input file '{}' (is it indexed with tabix/csi?)", args.input);
+	      return;
+	    }
+	  };
+	  let (contig, start, end) = match parse_region(region) {
+	    Some(r) => r,
+	    None => {
+	      error!("Invalid --region '{}', expected 'chr', 'chr:pos' or 'chr:start-end'", region);
+	      return;
+	    }
+	  };
+	  let rid = match reader.header().name2rid(contig.as_bytes()) {
+	    Ok(rid) => rid,
+	    Err(_) => {
+	      error!("Unknown contig '{}' in --region", contig);
+	      return;
+	    }
+	  };
+	  if reader.fetch(rid, start, end).is_err() {
+	    error!("Can not seek to region '{}'", region);
+	    return;
+	  }
+	  Input::Indexed(reader)
+	} else if args.input == "-" {
     debug!("Reading from STDIN");
-    Reader::from_stdin().expect("Can not open input stream")
+    Input::Plain(Reader::from_stdin().expect("Can not open input stream"))
   } else {
     debug!("Opening input VCF at {}", args.input);
-    Reader::from_path(&args.input).expect("Can not open input VCF file")
+    Input::Plain(Reader::from_path(&args.input).expect("Can not open input VCF file"))
   };
 
   trace!("Extracting header information");
 	let header = input.header();
-	if header.sample_count() != 1 {
-		error!("input is not a single-sample VCF");
+	let n_samples = header.sample_count() as usize;
+	if args.reverse && n_samples != 1 {
+		error!("'--reverse' only supports single-sample input");
+		return;
+	} else if n_samples == 0 {
+		error!("input VCF has no samples; nothing to transfer fields into");
+		return;
+	} else if n_samples != 1 && !args.broadcast {
+		error!("input is not a single-sample VCF, use '--broadcast' to process multi-sample input");
 		return;
 	}
 
+	// Parse the per-field '--rename OLD:NEW' overrides
+	let mut renames: BTreeMap<String, String> = BTreeMap::new();
+	for r in &args.rename {
+		match r.split_once(':') {
+			Some((old, new)) => { renames.insert(old.to_owned(), new.to_owned()); },
+			None => {
+				error!("Invalid --rename '{}', expected 'OLD:NEW'", r);
+				return;
+			}
+		}
+	}
+
+	// Existing FORMAT IDs in the input header, used to detect collisions with transferred
+	// fields; also grows with each field's chosen output id as it's assigned below, so two
+	// transferred fields can't collide with each other (e.g. via the same --rename/--prefix).
+	let mut existing_format_ids: BTreeSet<String> = BTreeSet::new();
+	for rec in header.header_records() {
+		if let HeaderRecord::Format { key: _, values: v } = rec {
+			if let Some(id) = v.get("ID") {
+				existing_format_ids.insert(id.to_owned());
+			}
+		}
+	}
+
   trace!("Building new header");
 	let mut new_header = Header::from_template(header);
-	// Store a map of field-names to data types
+	// In forward mode we lift INFO fields into FORMAT; in --reverse mode it's the other way round.
+	let (source_kind, target_kind) = if args.reverse { ("FORMAT", "INFO") } else { ("INFO", "FORMAT") };
+	// Store a map of field-names to data types, and the (possibly renamed) output tag to write them under
 	let mut field_types = BTreeMap::new();
+	let mut output_names: BTreeMap<String, String> = BTreeMap::new();
+	// Expected element count per field, used to size the missing-value sentinel for
+	// broadcast so it matches the field's real (possibly multi-valued) cardinality;
+	// seeded from the header's declared Number where that's a fixed integer, and kept
+	// up to date with the last-seen value's length for 'A'/'G'/'.'-arity fields.
+	let mut field_lens: BTreeMap<String, usize> = BTreeMap::new();
 	for rec in header.header_records() {
-	  if let HeaderRecord::Info { key: _, values: v } = rec {
+	  let v = match (&args.reverse, rec) {
+	    (false, HeaderRecord::Info { key: _, values: v }) => Some(v),
+	    (true, HeaderRecord::Format { key: _, values: v }) => Some(v),
+	    _ => None,
+	  };
+	  if let Some(v) = v {
 			if let Some(id) = v.get("ID") {
 				if fields.contains(id) {
 					fields.remove(id);
-					new_header.remove_info(id.as_bytes());
+					if args.reverse {
+						new_header.remove_format(id.as_bytes());
+					} else {
+						new_header.remove_info(id.as_bytes());
+					}
 
-					let new_record = format!("##FORMAT=<ID={},Number={},Type={},Description={}>",
-						v.get("ID").unwrap(), v.get("Number").unwrap(), v.get("Type").unwrap(), v.get("Description").unwrap()
+					// Only the INFO->FORMAT direction can collide with an existing FORMAT tag
+					let output_id = if target_kind == "FORMAT" {
+						let customized = renames.contains_key(id) || args.prefix.is_some();
+						let renamed = renames.get(id).cloned()
+							.or_else(|| args.prefix.as_ref().map(|p| format!("{}{}", p, id)))
+							.unwrap_or_else(|| id.to_owned());
+						if existing_format_ids.contains(&renamed) {
+							if customized {
+								error!("FORMAT tag '{}' (renamed from INFO field '{}') still collides with an existing FORMAT tag; choose a different --rename or --prefix", renamed, id);
+							} else {
+								error!("FORMAT tag '{}' already exists in the header; use --prefix or --rename {}:<NEW> to avoid the collision", renamed, id);
+							}
+							return;
+						}
+						existing_format_ids.insert(renamed.clone());
+						renamed
+					} else {
+						id.to_owned()
+					};
+					output_names.insert(id.to_owned(), output_id.clone());
+
+					let new_record = format!("##{}=<ID={},Number={},Type={},Description={}>",
+						target_kind, output_id, v.get("Number").unwrap(), v.get("Type").unwrap(), v.get("Description").unwrap()
 					);
-					trace!("Adding new FORMAT header record: {}", new_record);
+					trace!("Adding new {} header record: {}", target_kind, new_record);
 					new_header.push_record(new_record.as_bytes());
 
 					match v.get("Type").unwrap().as_str() {
-						"Flag" => field_types.insert(id.to_owned(), header::TagType::Flag),
-						"Integer" => field_types.insert(id.to_owned(), header::TagType::Integer),
-						"Float" => field_types.insert(id.to_owned(), header::TagType::Float),
-						"String" => field_types.insert(id.to_owned(), header::TagType::String),
+						"Flag" => { field_types.insert(id.to_owned(), header::TagType::Flag); },
+						"Integer" => {
+							field_types.insert(id.to_owned(), header::TagType::Integer);
+							field_lens.insert(id.to_owned(), v.get("Number").and_then(|n| n.parse().ok()).unwrap_or(1));
+						},
+						"Float" => {
+							field_types.insert(id.to_owned(), header::TagType::Float);
+							field_lens.insert(id.to_owned(), v.get("Number").and_then(|n| n.parse().ok()).unwrap_or(1));
+						},
+						"String" => {
+							field_types.insert(id.to_owned(), header::TagType::String);
+							field_lens.insert(id.to_owned(), v.get("Number").and_then(|n| n.parse().ok()).unwrap_or(1));
+						},
 						_ => {
 							error!("Unknown tag type {}", v.get("Type").unwrap());
-							return();
+							return;
 						}
 					};
 
@@ -112,10 +355,10 @@ fn main() {
 			}
 		}
 	}
-	trace!("Found {} INFO fields: {:?}", field_types.len(), field_types);
+	trace!("Found {} {} fields: {:?}", field_types.len(), source_kind, field_types);
 	for f in args.fields {
 		if !field_types.contains_key(&f) {
-			error!("Error: input VCF does not contain INFO tag '{}'", f);
+			error!("Error: input VCF does not contain {} tag '{}'", source_kind, f);
 			return;
 		}
 	}
@@ -126,26 +369,38 @@ fn main() {
 		new_header.push_record(new_record.as_bytes());
 	}
 
+	if args.filter {
+	  let new_record = "##FORMAT=<ID=FT,Number=1,Type=String,Description=\"Site-level FILTER status transferred onto the sample\">";
+		trace!("Adding new FORMAT header record: {}", new_record);
+		new_header.push_record(new_record.as_bytes());
+	}
+
+
+  let (out_format, out_uncompressed) = match resolve_output_format(&args.output, &args.output_type) {
+    Some(f) => f,
+    None => {
+      error!("Unknown --output-type '{}', expected one of b|u|z|v", args.output_type.as_deref().unwrap_or(""));
+      return;
+    }
+  };
 
   let mut output = if args.output == "-" {
     debug!("Writing to STDOUT");
-    Writer::from_stdout(&new_header, true, Format::Vcf).unwrap()
+    Writer::from_stdout(&new_header, out_uncompressed, out_format).unwrap()
   } else {
-    debug!("Opening output VCF at {}", args.output);
-    Writer::from_path(&args.output, &new_header, true, Format::Vcf).unwrap()
+    debug!("Opening output at {}", args.output);
+    Writer::from_path(&args.output, &new_header, out_uncompressed, out_format).unwrap()
   };
 
   // Cound the number of records that were processed
 	let mut n_records = 0;
-	for r in input.records() {
+	let mut rec = input.empty_record();
+	while let Some(r) = input.read(&mut rec) {
 		// Extract the record
-		let mut rec = match r {
-		  Ok(i) => i,
-		  Err(e) => {
-		    error!("Malformed VCF record: {}", e);
-		    return;
-		  }
-		};
+		if let Err(e) = r {
+		  error!("Malformed VCF record: {}", e);
+		  return;
+		}
 
     // Check verbose reporting
 		n_records += 1;
@@ -157,6 +412,46 @@ fn main() {
 		let mut data = BTreeMap::new();
     for (tag, ttype) in &field_types {
 			let tagb = tag.as_bytes();
+			if args.reverse {
+				match ttype {
+					header::TagType::Flag => {
+						if let Ok(v) = rec.format(tagb).integer() {
+							if v.first().and_then(|s| s.first()).copied().unwrap_or(0) != 0 {
+								data.insert(tag, TagValue::Flag(1));
+							}
+						}
+						rec.push_format_integer(tagb, &[]).expect("Can not remove FORMAT tag");
+					},
+					header::TagType::Integer => {
+						if let Ok(v) = rec.format(tagb).integer() {
+							if let Some(sample) = v.first() {
+								data.insert(tag, TagValue::Integer(sample.to_vec()));
+							}
+						}
+						rec.push_format_integer(tagb, &[]).expect("Can not remove FORMAT tag");
+					},
+					header::TagType::Float => {
+						if let Ok(v) = rec.format(tagb).float() {
+							if let Some(sample) = v.first() {
+								data.insert(tag, TagValue::Float(sample.to_vec()));
+							}
+						}
+						rec.push_format_float(tagb, &[]).expect("Can not remove FORMAT tag");
+					},
+					header::TagType::String => {
+						if let Ok(v) = rec.format(tagb).string() {
+							if let Some(sample) = v.first() {
+								data.insert(tag, TagValue::String(vec![sample.to_vec()]));
+							}
+						}
+						// push_format_string refuses empty data (it asserts non-empty), so use
+						// push_format_char instead, which shares the same BCF_HT_STR-typed
+						// bcf_update_format() call and applies the same n=0 clearing trick.
+						rec.push_format_char(tagb, &[]).expect("Can not remove FORMAT tag");
+					}
+				}
+				continue;
+			}
 			match ttype {
 				header::TagType::Flag => {
 					let v = rec.info(tagb).flag().unwrap();
@@ -170,51 +465,89 @@ fn main() {
 				header::TagType::Integer => {
 					if let Some(v) = rec.info(tagb).integer().unwrap() {
 						let x : Vec<i32> = v.iter().map(|x| *x).collect();
+						field_lens.insert(tag.to_owned(), x.len());
 						data.insert(tag, TagValue::Integer(x));
 						rec.clear_info_integer(tagb).expect("Can not remove INFO tag");
+					} else if args.broadcast {
+						let len = field_lens.get(tag).copied().unwrap_or(1);
+						data.insert(tag, TagValue::Integer(vec![i32::missing(); len]));
 					}
 				},
 				header::TagType::Float => {
 					if let Some(v) = rec.info(tagb).float().unwrap() {
 						let x : Vec<f32> = v.iter().map(|x| *x).collect();
+						field_lens.insert(tag.to_owned(), x.len());
 						data.insert(tag, TagValue::Float(x));
 						rec.clear_info_float(tagb).expect("Can not remove INFO tag");
+					} else if args.broadcast {
+						let len = field_lens.get(tag).copied().unwrap_or(1);
+						data.insert(tag, TagValue::Float(vec![f32::missing(); len]));
 					}
 				},
 				header::TagType::String => {
 					if let Some(v) = rec.info(tagb).string().unwrap() {
 						let x : Vec<Vec<u8>> = v.iter().map(|x| x.to_vec()).collect();
+						field_lens.insert(tag.to_owned(), x.len());
 						data.insert(tag, TagValue::String(x));
 						rec.clear_info_string(tagb).expect("Can not remove INFO tag");
+					} else if args.broadcast {
+						let len = field_lens.get(tag).copied().unwrap_or(1);
+						data.insert(tag, TagValue::String(vec![b".".to_vec(); len]));
 					}
 				}
 			}
 		}
 
+		let ft = if args.filter { Some(filter_string(&rec)) } else { None };
+
     // Replace the header for the record
 		output.translate(&mut rec);
 
-    // Re-Insert the data as Format tag
+    // Re-Insert the data, lifted into INFO in --reverse mode, FORMAT (broadcast to
+    // every sample if requested) otherwise
 		for (tag, data) in &data {
 			let tagb = tag.as_bytes();
+			if args.reverse {
+				match data {
+					TagValue::Flag(_) => {
+						rec.push_info_flag(tagb).expect("Can not store Flag in the INFO field");
+					},
+					TagValue::Integer(v) => {
+						rec.push_info_integer(tagb, v).expect("Can not store Integer in the INFO field");
+					},
+					TagValue::Float(v) => {
+						rec.push_info_float(tagb, v).expect("Can not store Float in the INFO field");
+					},
+					TagValue::String(v) => {
+						let refs : Vec<&[u8]> = v.iter().map(|s| s.as_slice()).collect();
+						rec.push_info_string(tagb, &refs).expect("Can not store String in the INFO field");
+					}
+				}
+				continue;
+			}
+			let out_tagb = output_names.get(*tag).map(|s| s.as_bytes()).unwrap_or(tagb);
 			match data {
 				TagValue::Flag(v) => {
-					rec.push_format_integer(tagb, &[*v]).expect("Can not store FLAG-Integer in the format");
+					rec.push_format_integer(out_tagb, &broadcast(&[*v], n_samples)).expect("Can not store FLAG-Integer in the format");
 				},
 				TagValue::Integer(v) => {
-					rec.push_format_integer(tagb, &v).expect("Can not store Integer in the format");
+					rec.push_format_integer(out_tagb, &broadcast(v, n_samples)).expect("Can not store Integer in the format");
 				},
 				TagValue::Float(v) => {
-					rec.push_format_float(tagb, &v).expect("Can not store Float in the format");
+					rec.push_format_float(out_tagb, &broadcast(v, n_samples)).expect("Can not store Float in the format");
 				},
 				TagValue::String(v) => {
-					rec.push_format_string(tagb, &v).expect("Can not store String in the format");
+					rec.push_format_string(out_tagb, &broadcast(v, n_samples)).expect("Can not store String in the format");
 				}
 			}
 		}
 		if args.qual {
 		  let q = [rec.qual()];
-      rec.push_format_float("QUAL".as_bytes(), &q).expect("Can not store Float in the format");
+      rec.push_format_float("QUAL".as_bytes(), &broadcast(&q, n_samples)).expect("Can not store Float in the format");
+		}
+
+		if let Some(ft) = &ft {
+			rec.push_format_string("FT".as_bytes(), &broadcast(&[ft.as_bytes().to_vec()], n_samples)).expect("Can not store FT in the format");
 		}
 
 